@@ -26,6 +26,13 @@ impl<R> Group<R> {
         self
     }
 
+    /// Pushes an already-boxed command to the back of the group.
+    #[inline]
+    pub(crate) fn push_boxed(&mut self, command: Box<Command<R>>) -> &mut Group<R> {
+        self.commands.push(command);
+        self
+    }
+
     /// Returns `true` if the group is empty.
     #[inline]
     pub fn is_empty(&self) -> bool {
@@ -0,0 +1,301 @@
+use std::collections::HashSet;
+use std::error;
+use std::fmt::{self, Debug, Formatter};
+use {Command, Error};
+
+type Id = usize;
+
+struct Node<R: 'static> {
+    parent: Option<Id>,
+    command: Box<Command<R>>,
+}
+
+impl<R> Debug for Node<R> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Node")
+            .field("parent", &self.parent)
+            .field("command", &self.command)
+            .finish()
+    }
+}
+
+/// A tree of commands.
+///
+/// Unlike [`Record`], which discards the commands ahead of the cursor as soon as a new command
+/// is pushed after an undo, `History` keeps every command ever pushed. Each command is stored as
+/// a node with a link to its parent, so pushing after an undo starts a new branch from the
+/// current position instead of overwriting the old one. The old sequence is still reachable as
+/// a separate branch, and [`go_to`] can jump between them.
+///
+/// [`Record`]: struct.Record.html
+/// [`go_to`]: struct.History.html#method.go_to
+pub struct History<R: 'static> {
+    receiver: R,
+    nodes: Vec<Node<R>>,
+    current: Option<Id>,
+}
+
+impl<R> History<R> {
+    /// Creates a new history.
+    #[inline]
+    pub fn new(receiver: impl Into<R>) -> History<R> {
+        History {
+            receiver: receiver.into(),
+            nodes: Vec::new(),
+            current: None,
+        }
+    }
+
+    /// Returns the number of commands in the history, across all branches.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if the history is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns `true` if the history can undo.
+    #[inline]
+    pub fn can_undo(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Returns a reference to the receiver.
+    #[inline]
+    pub fn as_receiver(&self) -> &R {
+        &self.receiver
+    }
+
+    /// Returns a mutable reference to the receiver.
+    #[inline]
+    pub fn as_mut_receiver(&mut self) -> &mut R {
+        &mut self.receiver
+    }
+
+    /// Consumes the history, returning the receiver.
+    #[inline]
+    pub fn into_receiver(self) -> R {
+        self.receiver
+    }
+
+    /// Pushes the command onto the current branch and executes its [`redo`] method.
+    ///
+    /// If the cursor is not at the tip of its branch, the command starts a new branch from the
+    /// current position instead of discarding the commands ahead of it.
+    ///
+    /// # Errors
+    /// If an error occurs, the error is returned together with the command so the caller may
+    /// decide what to do.
+    ///
+    /// [`redo`]: trait.Command.html#tymethod.redo
+    #[inline]
+    pub fn push(&mut self, command: impl Command<R> + 'static) -> Result<(), Error<R>> {
+        self.push_boxed(Box::new(command))
+    }
+
+    fn push_boxed(&mut self, mut command: Box<Command<R>>) -> Result<(), Error<R>> {
+        if let Err(e) = command.redo(&mut self.receiver) {
+            return Err(Error(command, e));
+        }
+        let id = self.nodes.len();
+        self.nodes.push(Node { parent: self.current, command });
+        self.current = Some(id);
+        Ok(())
+    }
+
+    /// Calls the [`undo`] method for the active command and moves the cursor to its parent.
+    ///
+    /// Returns `None` if there is nothing to undo.
+    ///
+    /// # Errors
+    /// Unlike [`push`], the command here already belongs to the tree rather than being lost on
+    /// failure, so only the error is returned, not the command.
+    ///
+    /// [`undo`]: trait.Command.html#tymethod.undo
+    /// [`push`]: struct.History.html#method.push
+    #[inline]
+    pub fn undo(&mut self) -> Option<Result<(), Box<error::Error>>> {
+        let id = self.current?;
+        let result = self.nodes[id].command.undo(&mut self.receiver);
+        self.current = self.nodes[id].parent;
+        Some(result)
+    }
+
+    /// Returns the ids of the branches in the tree, one for each leaf node.
+    #[inline]
+    pub fn branches(&self) -> Vec<usize> {
+        let mut has_child = vec![false; self.nodes.len()];
+        for node in &self.nodes {
+            if let Some(parent) = node.parent {
+                has_child[parent] = true;
+            }
+        }
+        (0..self.nodes.len()).filter(|&id| !has_child[id]).collect()
+    }
+
+    /// Moves the cursor to the given `index` along the branch that ends at `branch`, undoing
+    /// and redoing only the commands between the current position and the target.
+    ///
+    /// The `branch` is the id of a leaf node as returned by [`branches`], and `index` is the
+    /// number of commands applied from the root along that branch, so `index == 0` returns to
+    /// the initial state and `index == branch`'s depth moves to the leaf itself.
+    ///
+    /// Internally, this finds the lowest common ancestor of the current position and the
+    /// target, undoes back to it, and then redoes forward to the target, touching only the
+    /// commands on the path between the two.
+    ///
+    /// Returns `None` if `branch` does not exist or `index` is out of bounds for it.
+    ///
+    /// # Errors
+    /// As with [`undo`], every command touched here already belongs to the tree, so on failure
+    /// only the error is returned, not the command.
+    ///
+    /// [`branches`]: struct.History.html#method.branches
+    /// [`undo`]: struct.History.html#method.undo
+    pub fn go_to(&mut self, branch: usize, index: usize) -> Option<Result<(), Box<error::Error>>> {
+        if branch >= self.nodes.len() {
+            return None;
+        }
+        let path = self.path_to_root(branch);
+        if index > path.len() {
+            return None;
+        }
+        let target = if index == 0 { None } else { Some(path[index - 1]) };
+
+        let current_ancestors: HashSet<Id> = self.ancestors(self.current).into_iter().collect();
+        let target_ancestors = self.ancestors(target);
+        let lca = target_ancestors.iter().cloned().find(|id| current_ancestors.contains(id));
+
+        while self.current != lca {
+            let id = self.current.unwrap();
+            if let Err(e) = self.nodes[id].command.undo(&mut self.receiver) {
+                return Some(Err(e));
+            }
+            self.current = self.nodes[id].parent;
+        }
+
+        let redo_chain: Vec<Id> = target_ancestors
+            .into_iter()
+            .take_while(|&id| Some(id) != lca)
+            .collect();
+        for id in redo_chain.into_iter().rev() {
+            if let Err(e) = self.nodes[id].command.redo(&mut self.receiver) {
+                return Some(Err(e));
+            }
+            self.current = Some(id);
+        }
+
+        Some(Ok(()))
+    }
+
+    /// Returns an iterator over the commands on the current root-to-leaf path, in the order
+    /// they were applied.
+    #[inline]
+    pub fn path(&self) -> Path<R> {
+        Path { nodes: &self.nodes, stack: self.ancestors(self.current) }
+    }
+
+    /// Returns the ids of the node's ancestors, from `node` (inclusive) up to the root,
+    /// i.e. in leaf-to-root order.
+    fn ancestors(&self, node: Option<Id>) -> Vec<Id> {
+        let mut ancestors = Vec::new();
+        let mut cursor = node;
+        while let Some(id) = cursor {
+            ancestors.push(id);
+            cursor = self.nodes[id].parent;
+        }
+        ancestors
+    }
+
+    /// Returns the ids from the root down to `leaf` (inclusive), i.e. in root-to-leaf order.
+    fn path_to_root(&self, leaf: Id) -> Vec<Id> {
+        let mut path = self.ancestors(Some(leaf));
+        path.reverse();
+        path
+    }
+}
+
+impl<R: Default> Default for History<R> {
+    #[inline]
+    fn default() -> History<R> {
+        History::new(R::default())
+    }
+}
+
+impl<R: Debug> Debug for History<R> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("History")
+            .field("receiver", &self.receiver)
+            .field("nodes", &self.nodes)
+            .field("current", &self.current)
+            .finish()
+    }
+}
+
+/// An iterator over the commands on a root-to-leaf path of a [`History`].
+///
+/// [`History`]: struct.History.html
+#[derive(Debug)]
+pub struct Path<'a, R: 'static> {
+    nodes: &'a [Node<R>],
+    stack: Vec<Id>,
+}
+
+impl<'a, R> Iterator for Path<'a, R> {
+    type Item = &'a Command<R>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.stack.pop()?;
+        Some(&*self.nodes[id].command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Push(char);
+
+    impl Command<String> for Push {
+        fn redo(&mut self, receiver: &mut String) -> Result<(), Box<error::Error>> {
+            receiver.push(self.0);
+            Ok(())
+        }
+
+        fn undo(&mut self, receiver: &mut String) -> Result<(), Box<error::Error>> {
+            receiver.pop();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn go_to_jumps_between_branches() {
+        let mut history = History::new(String::new());
+        history.push(Push('a')).unwrap();
+        history.push(Push('b')).unwrap();
+        assert_eq!(history.as_receiver(), "ab");
+
+        history.undo().unwrap().unwrap();
+        history.push(Push('c')).unwrap();
+        assert_eq!(history.as_receiver(), "ac");
+
+        let branches = history.branches();
+        assert_eq!(branches.len(), 2);
+        let ab_branch = branches[0];
+        let ac_branch = branches[1];
+
+        history.go_to(ab_branch, 2).unwrap().unwrap();
+        assert_eq!(history.as_receiver(), "ab");
+
+        history.go_to(ac_branch, 1).unwrap().unwrap();
+        assert_eq!(history.as_receiver(), "a");
+    }
+}
@@ -2,27 +2,40 @@
 //! It uses the [command pattern](https://en.wikipedia.org/wiki/Command_pattern)
 //! where the user modifies a receiver by applying commands on it.
 //!
-//! The library has currently two data structures that can be used to modify the receiver:
+//! The library has currently three data structures that can be used to modify the receiver:
 //!
 //! * A stack that can push and pop commands to modify the receiver.
 //! * A record that can roll the state of the receiver forwards and backwards.
+//! * A history that works like the record but also allows jumping between branches created by
+//!   undoing and then diverging from an earlier point.
 
 #![forbid(unstable_features, bad_style)]
 #![deny(missing_debug_implementations, unused_import_braces, unused_qualifications, unsafe_code)]
 
 mod group;
+mod history;
 mod record;
 mod stack;
 
+use std::any::Any;
 use std::error;
 use std::fmt::{self, Debug, Display, Formatter};
 
 pub use group::Group;
+pub use history::{History, Path};
 pub use record::{Commands, Record, RecordBuilder};
-pub use stack::Stack;
+pub use stack::{Stack, StackBuilder};
 
 /// Base functionality for all commands.
-pub trait Command<R>: Debug {
+///
+/// The `Any` supertrait bound lets [`Record`] and [`Stack`] recover the concrete type behind a
+/// boxed command, which is how they call [`merge`] on two commands that share an [`id`].
+///
+/// [`Record`]: struct.Record.html
+/// [`Stack`]: struct.Stack.html
+/// [`merge`]: trait.Command.html#method.merge
+/// [`id`]: trait.Command.html#method.id
+pub trait Command<R>: Debug + Any {
     /// Executes the desired command and returns `Ok` if everything went fine, and `Err` if
     /// something went wrong.
     fn redo(&mut self, receiver: &mut R) -> Result<(), Box<error::Error>>;
@@ -42,6 +55,23 @@ pub trait Command<R>: Debug {
     fn id(&self) -> Option<u32> {
         None
     }
+
+    /// Used for explicit, value-level merging of two commands that share an [`id`].
+    ///
+    /// When a pushed command has the same id as the top command already on the stack, this is
+    /// tried first. Returning `true` means `other`'s data has been fused into `self`, so `other`
+    /// is dropped and undoing/redoing the pair now touches only `self`. Returning `false` (the
+    /// default) falls back to wrapping both commands in a `Merger`, as before.
+    ///
+    /// [`id`]: trait.Command.html#method.id
+    #[inline]
+    fn merge(&mut self, other: &Self) -> bool
+    where
+        Self: Sized,
+    {
+        let _ = other;
+        false
+    }
 }
 
 impl<R, C: Command<R> + ?Sized> Command<R> for Box<C> {
@@ -61,12 +91,12 @@ impl<R, C: Command<R> + ?Sized> Command<R> for Box<C> {
     }
 }
 
-struct Merger<R> {
+struct Merger<R: 'static> {
     cmd1: Box<Command<R>>,
     cmd2: Box<Command<R>>,
 }
 
-impl<R> Command<R> for Merger<R> {
+impl<R: 'static> Command<R> for Merger<R> {
     #[inline]
     fn redo(&mut self, receiver: &mut R) -> Result<(), Box<error::Error>> {
         self.cmd1.redo(receiver)?;
@@ -85,7 +115,7 @@ impl<R> Command<R> for Merger<R> {
     }
 }
 
-impl<R> Debug for Merger<R> {
+impl<R: 'static> Debug for Merger<R> {
     #[inline]
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.debug_struct("Merger")
@@ -95,6 +125,27 @@ impl<R> Debug for Merger<R> {
     }
 }
 
+/// Says something about the state of a [`Record`] or [`Stack`].
+///
+/// This is used by the [`signal`] function and tells the user when the structure transitions
+/// between the "can undo" / "can redo" boundary states, so that e.g. undo/redo UI buttons can be
+/// enabled or disabled without having to poll the structure.
+///
+/// [`Record`]: struct.Record.html
+/// [`Stack`]: struct.Stack.html
+/// [`signal`]: struct.RecordBuilder.html#method.signal
+#[derive(Debug)]
+pub enum Signal {
+    /// Says if the structure can redo.
+    Redo(bool),
+    /// Says if the structure can undo.
+    Undo(bool),
+    /// Says if the receiver is in the same state as it was when [`set_saved`] was last called.
+    ///
+    /// [`set_saved`]: struct.Record.html#method.set_saved
+    Saved(bool),
+}
+
 /// An error kind that holds the error and the command that caused the error.
 #[derive(Debug)]
 pub struct Error<R>(pub Box<Command<R>>, pub Box<error::Error>);
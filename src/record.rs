@@ -1,19 +1,26 @@
+use std::any::Any;
 use std::collections::VecDeque;
 use std::error;
 use std::fmt::{self, Debug, Formatter};
-use {Command, Error, Merger};
+use {Command, Error, Group, Merger, Signal};
 
 /// A record of commands.
 ///
 /// The record can roll the receivers state backwards and forwards by using the undo and redo
 /// methods. Commands that share an [`id`] and are pushed one after another are merged into a
-/// single command, so that they are undone and redone in one step.
+/// single command, so that they are undone and redone in one step. A record built with
+/// [`limit`] caps the number of commands it retains, discarding the oldest once the limit is
+/// reached.
 ///
 /// [`id`]: trait.Command.html#method.id
+/// [`limit`]: struct.RecordBuilder.html#method.limit
 pub struct Record<R: 'static> {
     commands: VecDeque<Box<Command<R>>>,
     receiver: R,
     cursor: usize,
+    saved: Option<usize>,
+    limit: Option<usize>,
+    signal: Option<Box<FnMut(Signal)>>,
 }
 
 impl<R> Record<R> {
@@ -24,6 +31,9 @@ impl<R> Record<R> {
             commands: VecDeque::new(),
             receiver: receiver.into(),
             cursor: 0,
+            saved: None,
+            limit: None,
+            signal: None,
         }
     }
 
@@ -81,38 +91,155 @@ impl<R> Record<R> {
         Commands(self.commands.iter())
     }
 
+    /// Marks the receiver's current state as saved.
+    ///
+    /// [`is_saved`] will return `true` until the cursor moves away from this position again.
+    ///
+    /// [`is_saved`]: struct.Record.html#method.is_saved
+    #[inline]
+    pub fn set_saved(&mut self) {
+        let was_undo = self.can_undo();
+        let was_redo = self.can_redo();
+        let was_saved = self.is_saved();
+        self.saved = Some(self.cursor);
+        self.emit(was_undo, was_redo, was_saved);
+    }
+
+    /// Returns `true` if the receiver is in the same state as it was when [`set_saved`] was
+    /// last called.
+    ///
+    /// Returns `false` if [`set_saved`] has never been called, or if the saved position was
+    /// discarded by a push that truncated the branch it was on.
+    ///
+    /// [`set_saved`]: struct.Record.html#method.set_saved
+    #[inline]
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.cursor)
+    }
+
+    /// Calls the signal, if one is set, for every boundary state that flipped between
+    /// `was_undo` / `was_redo` / `was_saved` and the record's current state.
+    fn emit(&mut self, was_undo: bool, was_redo: bool, was_saved: bool) {
+        let is_undo = self.can_undo();
+        let is_redo = self.can_redo();
+        let is_saved = self.is_saved();
+        if let Some(ref mut signal) = self.signal {
+            if was_undo != is_undo {
+                signal(Signal::Undo(is_undo));
+            }
+            if was_redo != is_redo {
+                signal(Signal::Redo(is_redo));
+            }
+            if was_saved != is_saved {
+                signal(Signal::Saved(is_saved));
+            }
+        }
+    }
+
     /// Pushes the command on top of the record and executes its [`redo`] method.
     ///
     /// All commands above the active one are discarded. If the id of the pushed command is equal
-    /// to the id of the top command on the record, the two commands are merged into one.
+    /// to the id of the top command on the record, [`merge`] is tried first; if it declines, the
+    /// two commands are wrapped in a `Merger` instead, so that undoing and redoing them are
+    /// still done in one step.
     ///
     /// # Errors
     /// If an error occurs, the error is returned together with the command so the caller may
     /// decide what to do.
     ///
     /// [`redo`]: trait.Command.html#tymethod.redo
+    /// [`merge`]: trait.Command.html#method.merge
     #[inline]
-    pub fn push(&mut self, command: impl Command<R> + 'static) -> Result<(), Error<R>> {
-        self.push_boxed(Box::new(command))
+    pub fn push<C: Command<R> + 'static>(&mut self, mut command: C) -> Result<(), Error<R>> {
+        if let Err(e) = command.redo(&mut self.receiver) {
+            return Err(Error(Box::new(command), e));
+        }
+        self.push_applied(command);
+        Ok(())
     }
 
-    fn push_boxed(&mut self, mut command: Box<Command<R>>) -> Result<(), Error<R>> {
-        if let Err(e) = command.redo(&mut self.receiver) {
-            return Err(Error(command, e));
+    /// Pushes many commands onto the record as a single logical step.
+    ///
+    /// Each command's [`redo`] is applied in order. If one of them errors, the commands applied
+    /// so far are undone and the error is returned together with the offending command, leaving
+    /// the receiver and the record exactly as they were before the call. Otherwise, the commands
+    /// are recorded as a single [`Group`], so undoing or redoing the batch is done in one step.
+    ///
+    /// [`redo`]: trait.Command.html#tymethod.redo
+    /// [`Group`]: struct.Group.html
+    pub fn extend<I>(&mut self, commands: I) -> Result<(), Error<R>>
+    where
+        I: IntoIterator<Item = Box<Command<R>>>,
+    {
+        let mut group = Group::new();
+        for mut command in commands {
+            if let Err(e) = command.redo(&mut self.receiver) {
+                let _ = group.undo(&mut self.receiver);
+                return Err(Error(command, e));
+            }
+            group.push_boxed(command);
+        }
+        if !group.is_empty() {
+            self.push_applied(group);
+        }
+        Ok(())
+    }
+
+    /// Records `command`, which has already been applied to the receiver, truncating any
+    /// commands ahead of the cursor and merging with the top command where possible.
+    fn push_applied<C: Command<R> + 'static>(&mut self, command: C) {
+        let was_undo = self.can_undo();
+        let was_redo = self.can_redo();
+        let was_saved = self.is_saved();
+
+        // The commands ahead of the cursor are about to be discarded. If the saved position was
+        // among them, it becomes unreachable and must never report saved again.
+        if self.saved.map_or(false, |saved| saved > self.cursor) {
+            self.saved = None;
         }
 
         self.commands.truncate(self.cursor);
 
-        match self.commands.back() {
-            Some(last) if last.id().is_some() && last.id() == command.id() => {
+        let same_id = self
+            .commands
+            .back()
+            .map_or(false, |last| last.id().is_some() && last.id() == command.id());
+
+        // Merging into the top command (whether `merge` succeeds or it falls back to a
+        // `Merger`) mutates that command without advancing the cursor, so a saved marker
+        // pointing at it no longer reflects the receiver's state.
+        if same_id && self.saved == Some(self.cursor) {
+            self.saved = None;
+        }
+
+        let merged = same_id && {
+            let last: &mut Any = &mut **self.commands.back_mut().unwrap();
+            last.downcast_mut::<C>().map_or(false, |last| last.merge(&command))
+        };
+
+        if !merged {
+            if same_id {
                 let cmd1 = self.commands.pop_back().unwrap();
-                command = Box::new(Merger { cmd1, cmd2: command });
+                self.commands.push_back(Box::new(Merger { cmd1, cmd2: Box::new(command) }));
+            } else {
+                self.cursor += 1;
+                self.commands.push_back(Box::new(command));
+
+                // The cursor was at the top, so growing the record may have pushed it past the
+                // limit. Drop the oldest command to make room; it can never be undone again.
+                if self.limit.map_or(false, |limit| self.commands.len() > limit) {
+                    self.commands.pop_front();
+                    self.cursor -= 1;
+                    self.saved = match self.saved {
+                        Some(0) => None,
+                        Some(saved) => Some(saved - 1),
+                        None => None,
+                    };
+                }
             }
-            _ => self.cursor += 1,
         }
 
-        self.commands.push_back(command);
-        Ok(())
+        self.emit(was_undo, was_redo, was_saved);
     }
 
     /// Calls the [`undo`] method for the active command and sets the previous one as the new
@@ -123,12 +250,17 @@ impl<R> Record<R> {
     /// [`undo`]: trait.Command.html#tymethod.undo
     #[inline]
     pub fn undo(&mut self) -> Option<Result<(), Box<error::Error>>> {
-        if !self.can_undo() {
+        let was_undo = self.can_undo();
+        let was_redo = self.can_redo();
+        let was_saved = self.is_saved();
+        if !was_undo {
             return None;
         }
         self.cursor -= 1;
         let command = &mut self.commands[self.cursor];
-        Some(command.undo(&mut self.receiver))
+        let result = command.undo(&mut self.receiver);
+        self.emit(was_undo, was_redo, was_saved);
+        Some(result)
     }
 
     /// Calls the [`redo`] method for the new active command.
@@ -138,12 +270,16 @@ impl<R> Record<R> {
     /// [`redo`]: trait.Command.html#tymethod.redo
     #[inline]
     pub fn redo(&mut self) -> Option<Result<(), Box<error::Error>>> {
-        if !self.can_redo() {
+        let was_undo = self.can_undo();
+        let was_redo = self.can_redo();
+        let was_saved = self.is_saved();
+        if !was_redo {
             return None;
         }
         let command = &mut self.commands[self.cursor];
         let result = command.redo(&mut self.receiver);
         self.cursor += 1;
+        self.emit(was_undo, was_redo, was_saved);
         Some(result)
     }
 }
@@ -162,26 +298,76 @@ impl<R: Debug> Debug for Record<R> {
             .field("commands", &self.commands)
             .field("receiver", &self.receiver)
             .field("cursor", &self.cursor)
+            .field("saved", &self.saved)
+            .field("limit", &self.limit)
+            .field("signal", &self.signal.is_some())
             .finish()
     }
 }
 
 /// Builder for a record.
-#[derive(Debug)]
 pub struct RecordBuilder<R: 'static> {
     receiver: ::std::marker::PhantomData<R>,
+    limit: Option<usize>,
+    signal: Option<Box<FnMut(Signal)>>,
 }
 
 impl<R> RecordBuilder<R> {
     #[inline]
     fn new() -> RecordBuilder<R> {
-        RecordBuilder { receiver: ::std::marker::PhantomData }
+        RecordBuilder {
+            receiver: ::std::marker::PhantomData,
+            limit: None,
+            signal: None,
+        }
+    }
+
+    /// Sets the signal that will be called when the state of the record changes.
+    ///
+    /// The signal is called each time the "can undo" or "can redo" state flips, which makes it
+    /// suitable for driving undo/redo UI elements without having to poll the record.
+    #[inline]
+    pub fn signal(mut self, signal: impl FnMut(Signal) + 'static) -> RecordBuilder<R> {
+        self.signal = Some(Box::new(signal));
+        self
+    }
+
+    /// Sets the maximum number of commands the record will retain.
+    ///
+    /// Once the limit is reached, pushing a new command at the top discards the oldest command
+    /// to make room. A dropped command can never be undone again, and if the [`saved`] position
+    /// falls within the dropped region it is invalidated, so [`is_saved`] will never return
+    /// `true` for that state again.
+    ///
+    /// [`saved`]: struct.Record.html#method.set_saved
+    /// [`is_saved`]: struct.Record.html#method.is_saved
+    #[inline]
+    pub fn limit(mut self, limit: usize) -> RecordBuilder<R> {
+        self.limit = Some(limit);
+        self
     }
 
     /// Builds the record with the given receiver.
     #[inline]
     pub fn build(self, receiver: impl Into<R>) -> Record<R> {
-        Record::new(receiver)
+        Record {
+            commands: VecDeque::new(),
+            receiver: receiver.into(),
+            cursor: 0,
+            saved: None,
+            limit: self.limit,
+            signal: self.signal,
+        }
+    }
+}
+
+impl<R> Debug for RecordBuilder<R> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("RecordBuilder")
+            .field("limit", &self.limit)
+            .field("signal", &self.signal.is_some())
+            .finish()
     }
 }
 
@@ -197,3 +383,78 @@ impl<'a, R> Iterator for Commands<'a, R> {
         self.0.next().map(|command| &**command)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct Add(char, Option<u32>);
+
+    impl Command<String> for Add {
+        fn redo(&mut self, receiver: &mut String) -> Result<(), Box<error::Error>> {
+            receiver.push(self.0);
+            Ok(())
+        }
+
+        fn undo(&mut self, receiver: &mut String) -> Result<(), Box<error::Error>> {
+            receiver.pop();
+            Ok(())
+        }
+
+        fn id(&self) -> Option<u32> {
+            self.1
+        }
+    }
+
+    #[derive(Debug)]
+    struct Fail;
+
+    impl fmt::Display for Fail {
+        fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+            write!(f, "fail")
+        }
+    }
+
+    impl error::Error for Fail {
+        fn description(&self) -> &str {
+            "fail"
+        }
+    }
+
+    impl Command<String> for Fail {
+        fn redo(&mut self, _: &mut String) -> Result<(), Box<error::Error>> {
+            Err(Box::new(Fail))
+        }
+
+        fn undo(&mut self, _: &mut String) -> Result<(), Box<error::Error>> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn same_id_merge_invalidates_saved() {
+        let mut record = Record::new(String::new());
+        record.push(Add('a', Some(1))).unwrap();
+        record.set_saved();
+        assert!(record.is_saved());
+
+        record.push(Add('b', Some(1))).unwrap();
+        assert_eq!(record.as_receiver(), "ab");
+        assert!(!record.is_saved());
+    }
+
+    #[test]
+    fn extend_rolls_back_on_error() {
+        let mut record = Record::new(String::new());
+        record.push(Add('a', None)).unwrap();
+
+        let commands: Vec<Box<Command<String>>> =
+            vec![Box::new(Add('b', None)), Box::new(Fail), Box::new(Add('c', None))];
+        let result = record.extend(commands);
+
+        assert!(result.is_err());
+        assert_eq!(record.as_receiver(), "a");
+        assert_eq!(record.len(), 1);
+    }
+}
@@ -1,16 +1,21 @@
+use std::any::Any;
 use std::fmt::{self, Debug, Formatter};
-use {Command, Error, Merger};
+use {Command, Error, Group, Merger, Signal};
 
 /// A stack of commands.
 ///
 /// The stack can push and pop commands to modify the receiver. Commands that share an [`id`]
 /// and are pushed one after another are merged into a single command, so that they are undone
-/// in one step.
+/// in one step. A stack built with [`limit`] caps the number of commands it retains, discarding
+/// the oldest once the limit is reached.
 ///
 /// [`id`]: trait.Command.html#method.id
+/// [`limit`]: struct.StackBuilder.html#method.limit
 pub struct Stack<R: 'static> {
     commands: Vec<Box<Command<R>>>,
     receiver: R,
+    limit: Option<usize>,
+    signal: Option<Box<FnMut(Signal)>>,
 }
 
 impl<R> Stack<R> {
@@ -20,9 +25,17 @@ impl<R> Stack<R> {
         Stack {
             commands: Vec::new(),
             receiver: receiver.into(),
+            limit: None,
+            signal: None,
         }
     }
 
+    /// Returns a builder for a stack.
+    #[inline]
+    pub fn builder() -> StackBuilder<R> {
+        StackBuilder::new()
+    }
+
     /// Returns the number of commands in the stack.
     #[inline]
     pub fn len(&self) -> usize {
@@ -35,6 +48,12 @@ impl<R> Stack<R> {
         self.commands.is_empty()
     }
 
+    /// Returns `true` if the stack can undo, i.e. has a command to pop.
+    #[inline]
+    pub fn can_undo(&self) -> bool {
+        !self.commands.is_empty()
+    }
+
     /// Returns a reference to the receiver.
     #[inline]
     pub fn as_receiver(&self) -> &R {
@@ -53,36 +72,96 @@ impl<R> Stack<R> {
         self.receiver
     }
 
+    /// Calls the signal, if one is set, when the "can undo" state flips between `was_undo` and
+    /// the stack's current state.
+    fn emit(&mut self, was_undo: bool) {
+        let is_undo = self.can_undo();
+        if was_undo != is_undo {
+            if let Some(ref mut signal) = self.signal {
+                signal(Signal::Undo(is_undo));
+            }
+        }
+    }
+
     /// Pushes the command on top of the stack and executes its [`redo`] method.
     ///
-    /// If the id of the pushed command is equal to the id of the top command on the stack, the
-    /// two commands are merged into one.
+    /// If the id of the pushed command is equal to the id of the top command on the stack,
+    /// [`merge`] is tried first; if it declines, the two commands are wrapped in a `Merger`
+    /// instead, so that undoing them is still done in one step.
     ///
     /// # Errors
     /// If an error occurs, the error is returned together with the command so the caller may
     /// decide what to do.
     ///
     /// [`redo`]: trait.Command.html#tymethod.redo
+    /// [`merge`]: trait.Command.html#method.merge
     #[inline]
-    pub fn push(&mut self, command: impl Command<R> + 'static) -> Result<(), Error<R>> {
-        self.push_boxed(Box::new(command))
+    pub fn push<C: Command<R> + 'static>(&mut self, mut command: C) -> Result<(), Error<R>> {
+        if let Err(e) = command.redo(&mut self.receiver) {
+            return Err(Error(Box::new(command), e));
+        }
+        self.push_applied(command);
+        Ok(())
     }
 
-    fn push_boxed(&mut self, mut command: Box<Command<R>>) -> Result<(), Error<R>> {
-        if let Err(e) = command.redo(&mut self.receiver) {
-            return Err(Error(command, e));
+    /// Pushes many commands onto the stack as a single logical step.
+    ///
+    /// Each command's [`redo`] is applied in order. If one of them errors, the commands applied
+    /// so far are undone and the error is returned together with the offending command, leaving
+    /// the receiver and the stack exactly as they were before the call. Otherwise, the commands
+    /// are recorded as a single [`Group`], so undoing the batch is done in one step.
+    ///
+    /// [`redo`]: trait.Command.html#tymethod.redo
+    /// [`Group`]: struct.Group.html
+    pub fn extend<I>(&mut self, commands: I) -> Result<(), Error<R>>
+    where
+        I: IntoIterator<Item = Box<Command<R>>>,
+    {
+        let mut group = Group::new();
+        for mut command in commands {
+            if let Err(e) = command.redo(&mut self.receiver) {
+                let _ = group.undo(&mut self.receiver);
+                return Err(Error(command, e));
+            }
+            group.push_boxed(command);
         }
+        if !group.is_empty() {
+            self.push_applied(group);
+        }
+        Ok(())
+    }
 
-        match self.commands.last() {
-            Some(last) if last.id().is_some() && last.id() == command.id() => {
+    /// Pushes `command`, which has already been applied to the receiver, merging with the top
+    /// command where possible.
+    fn push_applied<C: Command<R> + 'static>(&mut self, command: C) {
+        let was_undo = self.can_undo();
+
+        let same_id = self
+            .commands
+            .last()
+            .map_or(false, |last| last.id().is_some() && last.id() == command.id());
+
+        let merged = same_id && {
+            let last: &mut Any = &mut **self.commands.last_mut().unwrap();
+            last.downcast_mut::<C>().map_or(false, |last| last.merge(&command))
+        };
+
+        if !merged {
+            if same_id {
                 let cmd1 = self.commands.pop().unwrap();
-                command = Box::new(Merger { cmd1, cmd2: command });
+                self.commands.push(Box::new(Merger { cmd1, cmd2: Box::new(command) }));
+            } else {
+                self.commands.push(Box::new(command));
+
+                // Pushing grew the stack, so it may now be over the limit. Drop the oldest
+                // command to make room; it can never be undone again.
+                if self.limit.map_or(false, |limit| self.commands.len() > limit) {
+                    self.commands.remove(0);
+                }
             }
-            _ => (),
         }
 
-        self.commands.push(command);
-        Ok(())
+        self.emit(was_undo);
     }
 
     /// Pops the top command off the stack and executes its [`undo`] method.
@@ -92,11 +171,14 @@ impl<R> Stack<R> {
     /// [`undo`]: trait.Command.html#tymethod.undo
     #[inline]
     pub fn pop(&mut self) -> Option<Result<(), Error<R>>> {
+        let was_undo = self.can_undo();
         let mut command = self.commands.pop()?;
-        Some(match command.undo(&mut self.receiver) {
+        let result = match command.undo(&mut self.receiver) {
             Ok(()) => Ok(()),
             Err(e) => Err(Error(command, e)),
-        })
+        };
+        self.emit(was_undo);
+        Some(result)
     }
 }
 
@@ -113,6 +195,64 @@ impl<R: Debug> Debug for Stack<R> {
         f.debug_struct("Stack")
             .field("commands", &self.commands)
             .field("receiver", &self.receiver)
+            .field("limit", &self.limit)
+            .field("signal", &self.signal.is_some())
+            .finish()
+    }
+}
+
+/// Builder for a stack.
+pub struct StackBuilder<R: 'static> {
+    receiver: ::std::marker::PhantomData<R>,
+    limit: Option<usize>,
+    signal: Option<Box<FnMut(Signal)>>,
+}
+
+impl<R> StackBuilder<R> {
+    #[inline]
+    fn new() -> StackBuilder<R> {
+        StackBuilder {
+            receiver: ::std::marker::PhantomData,
+            limit: None,
+            signal: None,
+        }
+    }
+
+    /// Sets the signal that will be called when the "can undo" state of the stack flips.
+    #[inline]
+    pub fn signal(mut self, signal: impl FnMut(Signal) + 'static) -> StackBuilder<R> {
+        self.signal = Some(Box::new(signal));
+        self
+    }
+
+    /// Sets the maximum number of commands the stack will retain.
+    ///
+    /// Once the limit is reached, pushing a new command discards the oldest command at the
+    /// bottom of the stack to make room. A dropped command can never be undone again.
+    #[inline]
+    pub fn limit(mut self, limit: usize) -> StackBuilder<R> {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Builds the stack with the given receiver.
+    #[inline]
+    pub fn build(self, receiver: impl Into<R>) -> Stack<R> {
+        Stack {
+            commands: Vec::new(),
+            receiver: receiver.into(),
+            limit: self.limit,
+            signal: self.signal,
+        }
+    }
+}
+
+impl<R> Debug for StackBuilder<R> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("StackBuilder")
+            .field("limit", &self.limit)
+            .field("signal", &self.signal.is_some())
             .finish()
     }
 }